@@ -0,0 +1,58 @@
+// Copyright 2016 union-find-rs Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Struct and methods for union-find operation.
+
+mod quick_union;
+
+pub use quick_union::{QuickUnionUf, Snapshot, UnificationTable, UnifyKey, UnifyValue};
+
+/// Which side of a [`Union::union`] call kept the combined value.
+pub enum UnionResult<V> {
+    Left(V),
+    Right(V),
+}
+
+/// A value that can be combined with another instance of itself when the two
+/// sets containing them are merged.
+pub trait Union {
+    /// Merge `left` and `right`, returning the combined value tagged with
+    /// which side of the union it's attached to.
+    fn union(left: Self, right: Self) -> UnionResult<Self>
+    where
+        Self: Sized;
+
+    /// Reset a value that remains the payload of a singleton after
+    /// [`QuickUnionUf::clear`](crate::QuickUnionUf::clear) resets its set back to
+    /// singletons. No-op by default; override it for values that accumulate state
+    /// across unions and need resetting to use the arena again.
+    fn clear(&mut self) {}
+}
+
+/// Trait of management data structure for disjoint-set forests.
+pub trait UnionFind<V> {
+    /// The number of elements stored, including ones already merged into
+    /// another set.
+    fn size(&self) -> usize;
+
+    /// Add a new element to its own singleton set, returning its key.
+    fn insert(&mut self, data: V) -> usize;
+
+    /// Merge the sets containing `key0` and `key1`. Returns whether a merge
+    /// actually happened (`false` if they were already in the same set).
+    fn union(&mut self, key0: usize, key1: usize) -> bool;
+
+    /// The representative key of the set containing `key`.
+    fn find(&self, key: usize) -> usize;
+
+    /// The value attached to the representative of `key`'s set.
+    fn get(&self, key: usize) -> &V;
+
+    /// A mutable reference to the value attached to the representative of
+    /// `key`'s set.
+    fn get_mut(&mut self, key: usize) -> &mut V;
+}
@@ -8,13 +8,154 @@
 use dashmap::DashMap;
 
 use crate::{Union, UnionFind, UnionResult};
+use std::cell::UnsafeCell;
 use std::iter::FromIterator;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// One mutation recorded by [`QuickUnionUf`]'s undo log while a [`Snapshot`] is live,
+/// so [`QuickUnionUf::rollback_to`] can restore it.
+#[derive(Debug, Clone)]
+enum UndoEntry<V> {
+    /// `link_parent[key]` was overwritten; it used to be `old_parent`.
+    LinkChanged { key: usize, old_parent: usize },
+    /// `payload[key]` was overwritten; it used to be `old_value`.
+    PayloadChanged { key: usize, old_value: Option<V> },
+}
+
+/// An opaque marker returned by [`QuickUnionUf::snapshot`], later passed to
+/// [`QuickUnionUf::rollback_to`] or [`QuickUnionUf::commit`]. Snapshots nest like a
+/// stack: rolling back or committing always refers to the most specific open region.
+#[derive(Debug, Clone, Copy)]
+pub struct Snapshot(usize);
 
 /// Union-Find implementation with quick union operation.
-#[derive(Debug)]
+///
+/// With the `serde` feature enabled (which also turns on `dashmap`'s own `serde`
+/// feature), `QuickUnionUf<V>` implements `Serialize`/`Deserialize` so a constructed
+/// partition can be snapshotted to disk or sent between processes and reloaded
+/// without replaying every [`union`](UnionFind::union) call; key indices round-trip
+/// exactly, so `find`/`get` on a deserialized instance agree with the original.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(bound(
+        serialize = "V: serde::Serialize",
+        deserialize = "V: serde::de::DeserializeOwned"
+    ))
+)]
 pub struct QuickUnionUf<V> {
     link_parent: DashMap<usize, usize>,
-    payload: Vec<Option<V>>,
+    // Wrapped in `UnsafeCell` (rather than a plain `Vec`) so `try_union` can merge
+    // payloads through a shared `&self`; see the `SAFETY` note on its `unsafe impl
+    // Sync` below for the invariant that makes this sound.
+    #[cfg_attr(feature = "serde", serde(with = "payload_serde"))]
+    payload: UnsafeCell<Vec<Option<V>>>,
+    /// Mutations since the oldest open snapshot, in the order they happened.
+    #[cfg_attr(feature = "serde", serde(skip, default))]
+    undo_log: Mutex<Vec<UndoEntry<V>>>,
+    /// Number of nested snapshots currently open; `find`'s path-compression writes
+    /// only bother logging themselves while this is non-zero.
+    #[cfg_attr(feature = "serde", serde(skip, default))]
+    snapshot_depth: AtomicUsize,
+    /// Reusable scratch space for [`find_compress`](Self::find_compress), kept as a
+    /// field so repeated calls don't reallocate it. Always empty between calls.
+    #[cfg_attr(feature = "serde", serde(skip, default))]
+    find_parent_list: Vec<usize>,
+    /// Striped locks guarding individual payload slots against concurrent access:
+    /// slot `key` is protected by `payload_locks[key % payload_locks.len()]`.
+    /// [`try_union`](Self::try_union) takes the stripe(s) for the roots it's
+    /// merging before touching their slots, and [`get`](UnionFind::get) takes its
+    /// root's stripe before reading, so the two can never observe or produce a
+    /// torn payload. Sized independently of the element count (see
+    /// [`PAYLOAD_LOCK_SHARDS`]) so two `try_union` calls on unrelated roots
+    /// usually land in different stripes and don't contend with each other.
+    #[cfg_attr(feature = "serde", serde(skip, default = "new_payload_locks"))]
+    payload_locks: Vec<Mutex<()>>,
+}
+
+/// Number of stripes in `QuickUnionUf::payload_locks`. Fixed rather than scaled to
+/// the element count: a handful of stripes is enough to let unrelated concurrent
+/// merges proceed in parallel without paying for one lock per element.
+const PAYLOAD_LOCK_SHARDS: usize = 64;
+
+fn new_payload_locks() -> Vec<Mutex<()>> {
+    (0..PAYLOAD_LOCK_SHARDS).map(|_| Mutex::new(())).collect()
+}
+
+// SAFETY: every write to `payload` happens either behind `&mut self` (so the borrow
+// checker already guarantees exclusivity), or from `try_union`'s concurrent path,
+// which only ever touches a root's slot while holding that root's stripe of
+// `payload_locks`. `get` takes the same stripe before reading a slot, so it can never
+// observe a slot `try_union` has mid-merge, nor alias a `&mut V` `try_union` is
+// currently writing through. `try_union`'s unsafe block also takes care to only ever
+// form a *shared* reference to the backing `Vec` (via `as_ptr`, never `as_mut_ptr`),
+// since plain readers like `get`/`size`/`Debug::fmt` may be forming their own shared
+// reference to that same `Vec` concurrently from another thread.
+//
+// `size` and `Debug::fmt` don't take a stripe lock: `size` only reads the `Vec`'s
+// length, which `try_union` never changes (it only ever overwrites existing slots),
+// so it can't race with a concurrent merge. `Debug::fmt` is diagnostic-only and reads
+// every slot at once, which a per-slot stripe lock can't protect mid-format; don't
+// call it while a `try_union` call might be concurrently in flight on the same table.
+unsafe impl<V: Send> Sync for QuickUnionUf<V> {}
+
+#[cfg(feature = "serde")]
+mod payload_serde {
+    use super::*;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<V: Serialize, S: Serializer>(
+        payload: &UnsafeCell<Vec<Option<V>>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        // SAFETY: `&self` here comes from `Serialize::serialize(&QuickUnionUf<V>, _)`,
+        // so no `&mut self` call can be in flight concurrently.
+        unsafe { &*payload.get() }.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, V: Deserialize<'de>, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<UnsafeCell<Vec<Option<V>>>, D::Error> {
+        Vec::deserialize(deserializer).map(UnsafeCell::new)
+    }
+}
+
+/// Don't call this while another thread might be concurrently calling
+/// [`try_union`](QuickUnionUf::try_union) on the same table: unlike
+/// [`get`](UnionFind::get), formatting reads every slot in one pass, which a
+/// per-slot stripe lock can't protect mid-format.
+impl<V: std::fmt::Debug> std::fmt::Debug for QuickUnionUf<V> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("QuickUnionUf")
+            .field("link_parent", &self.link_parent)
+            .field("payload", self.payload())
+            .finish()
+    }
+}
+
+impl<V> QuickUnionUf<V> {
+    /// Shared view of the payload vector.
+    ///
+    /// SAFETY: valid as long as no `&mut self` method and no other call into
+    /// `try_union`'s concurrent merge path touch the *same* slot concurrently; see
+    /// the `unsafe impl Sync` above.
+    #[inline]
+    fn payload(&self) -> &Vec<Option<V>> {
+        unsafe { &*self.payload.get() }
+    }
+
+    /// The stripe of `payload_locks` guarding slot `key`.
+    #[inline]
+    fn payload_lock_shard(&self, key: usize) -> &Mutex<()> {
+        &self.payload_locks[key % self.payload_locks.len()]
+    }
+
+    /// Exclusive view of the payload vector, available wherever `&mut self` is.
+    #[inline]
+    fn payload_mut(&mut self) -> &mut Vec<Option<V>> {
+        self.payload.get_mut()
+    }
 }
 
 impl<V: Clone> Clone for QuickUnionUf<V> {
@@ -22,28 +163,39 @@ impl<V: Clone> Clone for QuickUnionUf<V> {
     fn clone(&self) -> QuickUnionUf<V> {
         QuickUnionUf {
             link_parent: self.link_parent.clone(),
-            payload: self.payload.clone(),
+            payload: UnsafeCell::new(self.payload().clone()),
+            undo_log: Mutex::new(self.undo_log.lock().unwrap().clone()),
+            snapshot_depth: AtomicUsize::new(self.snapshot_depth.load(Ordering::SeqCst)),
+            find_parent_list: Vec::new(),
+            payload_locks: new_payload_locks(),
         }
     }
 
     #[inline]
     fn clone_from(&mut self, other: &QuickUnionUf<V>) {
         self.link_parent.clone_from(&other.link_parent);
-        self.payload.clone_from(&other.payload);
+        self.payload_mut().clone_from(other.payload());
+        self.undo_log = Mutex::new(other.undo_log.lock().unwrap().clone());
+        self.snapshot_depth = AtomicUsize::new(other.snapshot_depth.load(Ordering::SeqCst));
+        self.find_parent_list.clear();
+        self.payload_locks = new_payload_locks();
     }
 }
 
-impl<V: Union> UnionFind<V> for QuickUnionUf<V> {
+// `Clone` is required (in addition to `Union`) so `union` can record the pre-merge
+// payloads in the undo log for `rollback_to` without consuming the values `Union::union`
+// needs to move.
+impl<V: Union + Clone> UnionFind<V> for QuickUnionUf<V> {
     #[inline]
     fn size(&self) -> usize {
-        self.payload.len()
+        self.payload().len()
     }
 
     #[inline]
     fn insert(&mut self, data: V) -> usize {
-        let key = self.payload.len();
+        let key = self.payload().len();
         let _ = self.link_parent.insert(key, key);
-        self.payload.push(Some(data));
+        self.payload_mut().push(Some(data));
         key
     }
 
@@ -56,14 +208,33 @@ impl<V: Union> UnionFind<V> for QuickUnionUf<V> {
         }
 
         // Temporary replace with dummy to move out the elements of the vector.
-        let v0 = self.payload[k0].take().unwrap();
-        let v1 = self.payload[k1].take().unwrap();
+        let v0 = self.payload_mut()[k0].take().unwrap();
+        let v1 = self.payload_mut()[k1].take().unwrap();
+
+        if self.snapshot_depth.load(Ordering::SeqCst) > 0 {
+            let mut log = self.undo_log.lock().unwrap();
+            log.push(UndoEntry::PayloadChanged {
+                key: k0,
+                old_value: Some(v0.clone()),
+            });
+            log.push(UndoEntry::PayloadChanged {
+                key: k1,
+                old_value: Some(v1.clone()),
+            });
+        }
 
         let (parent, child, val) = match Union::union(v0, v1) {
             UnionResult::Left(val) => (k0, k1, val),
             UnionResult::Right(val) => (k1, k0, val),
         };
-        self.payload[parent] = Some(val);
+        self.payload_mut()[parent] = Some(val);
+
+        if self.snapshot_depth.load(Ordering::SeqCst) > 0 {
+            self.undo_log.lock().unwrap().push(UndoEntry::LinkChanged {
+                key: child,
+                old_parent: child,
+            });
+        }
         let _ = self.link_parent.insert(child, parent);
 
         true
@@ -75,6 +246,12 @@ impl<V: Union> UnionFind<V> for QuickUnionUf<V> {
         let mut p = *self.link_parent.get(&k).unwrap();
         while p != k {
             let pp = *self.link_parent.get(&p).unwrap();
+            if self.snapshot_depth.load(Ordering::SeqCst) > 0 {
+                self.undo_log.lock().unwrap().push(UndoEntry::LinkChanged {
+                    key: k,
+                    old_parent: p,
+                });
+            }
             let _ = self.link_parent.insert(k, pp);
             k = p;
             p = pp;
@@ -84,14 +261,262 @@ impl<V: Union> UnionFind<V> for QuickUnionUf<V> {
 
     #[inline]
     fn get(&self, key: usize) -> &V {
-        let root_key = self.find(key);
-        self.payload[root_key].as_ref().unwrap()
+        loop {
+            let root_key = self.find(key);
+            // Rendezvous with any `try_union` call that's mid-merge on this root: taking
+            // its stripe blocks us until the slot is back to a stable `Some`, and blocks
+            // a `try_union` that arrives after us until we're done reading. See the
+            // `unsafe impl Sync` SAFETY note above.
+            let _guard = self.payload_lock_shard(root_key).lock().unwrap();
+            // If that merge made `root_key` the loser, its slot is now empty; look
+            // up the new root instead.
+            if *self.link_parent.get(&root_key).unwrap() != root_key {
+                continue;
+            }
+            return self.payload()[root_key].as_ref().unwrap();
+        }
     }
 
     #[inline]
     fn get_mut(&mut self, key: usize) -> &mut V {
         let root_key = self.find(key);
-        self.payload[root_key].as_mut().unwrap()
+        self.payload_mut()[root_key].as_mut().unwrap()
+    }
+}
+
+impl<V: Union + Clone> QuickUnionUf<V> {
+    /// Open a new speculative region: mutations made after this call (by `union`,
+    /// `find`'s path compression, or further nested snapshots) can be undone with
+    /// [`rollback_to`](Self::rollback_to) or discarded with [`commit`](Self::commit).
+    /// Snapshots nest like a stack.
+    pub fn snapshot(&mut self) -> Snapshot {
+        self.snapshot_depth.fetch_add(1, Ordering::SeqCst);
+        Snapshot(self.undo_log.lock().unwrap().len())
+    }
+
+    /// Undo every mutation recorded since `snapshot` was taken, restoring parent
+    /// links and payloads to the values they had at that point.
+    pub fn rollback_to(&mut self, snapshot: Snapshot) {
+        let mut log = self.undo_log.lock().unwrap();
+        while log.len() > snapshot.0 {
+            match log.pop().unwrap() {
+                UndoEntry::LinkChanged { key, old_parent } => {
+                    let _ = self.link_parent.insert(key, old_parent);
+                }
+                UndoEntry::PayloadChanged { key, old_value } => {
+                    self.payload.get_mut()[key] = old_value;
+                }
+            }
+        }
+        self.snapshot_depth.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    /// Keep every mutation recorded since `snapshot` was taken. Once the outermost
+    /// snapshot is committed, the whole undo log is dropped.
+    pub fn commit(&mut self, _snapshot: Snapshot) {
+        if self.snapshot_depth.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.undo_log.lock().unwrap().clear();
+        }
+    }
+
+    /// Overwrite `payload[key]` with `new_value`, logging the slot's previous value
+    /// first if a snapshot is currently open so [`rollback_to`](Self::rollback_to)
+    /// can restore it. Shared by every method that writes a payload slot outside of
+    /// `union`/`find`'s own logging.
+    fn set_payload_logged(&mut self, key: usize, new_value: Option<V>) {
+        if self.snapshot_depth.load(Ordering::SeqCst) > 0 {
+            let old_value = self.payload.get_mut()[key].take();
+            self.undo_log
+                .lock()
+                .unwrap()
+                .push(UndoEntry::PayloadChanged { key, old_value });
+        }
+        self.payload.get_mut()[key] = new_value;
+    }
+
+    /// Merge the sets containing `key0` and `key1` using a caller-supplied fallible
+    /// `merge` over the two roots' payloads, keeping the merged value at whichever
+    /// root `key0` resolves to. If `merge` returns `Err`, the partition is left
+    /// completely unchanged: no link is reseated and no payload is overwritten, so
+    /// this pairs naturally with [`snapshot`](Self::snapshot)/[`rollback_to`](Self::rollback_to)
+    /// for speculative inference. Returns `Ok(false)` without calling `merge` if the
+    /// keys were already in the same set.
+    pub fn try_merge<E>(
+        &mut self,
+        key0: usize,
+        key1: usize,
+        merge: impl FnOnce(&V, &V) -> Result<V, E>,
+    ) -> Result<bool, E> {
+        let k0 = self.find(key0);
+        let k1 = self.find(key1);
+        if k0 == k1 {
+            return Ok(false);
+        }
+
+        let merged = merge(
+            self.payload()[k0].as_ref().unwrap(),
+            self.payload()[k1].as_ref().unwrap(),
+        )?;
+
+        self.set_payload_logged(k1, None);
+        self.set_payload_logged(k0, Some(merged));
+
+        if self.snapshot_depth.load(Ordering::SeqCst) > 0 {
+            self.undo_log.lock().unwrap().push(UndoEntry::LinkChanged {
+                key: k1,
+                old_parent: k1,
+            });
+        }
+        let _ = self.link_parent.insert(k1, k0);
+
+        Ok(true)
+    }
+}
+
+impl<V: Union + Clone + Send> QuickUnionUf<V> {
+    /// Concurrent counterpart to [`union`](UnionFind::union): unions the sets
+    /// containing `key0` and `key1` through a shared `&self`, so it can be called
+    /// from multiple threads at once (e.g. through an `Arc<QuickUnionUf<V>>`).
+    /// Returns whether this call performed the merge, matching `union`'s semantics.
+    ///
+    /// [`find`](UnionFind::find) already path-halves through lock-free re-reads and
+    /// is safe to call concurrently; this adds the union side by claiming the loser
+    /// root under the winner's and loser's `payload_locks` stripes and re-pointing
+    /// the loser's `link_parent` entry at the winner. Holding those stripes for the
+    /// whole claim-and-merge section (not just the `link_parent` write) matters: two
+    /// calls that pick the *same* winner but different losers would otherwise both
+    /// believe they alone may touch the winner's payload slot, which a CAS on the
+    /// loser's entry alone can't prevent. Plain `&self` readers like
+    /// [`get`](UnionFind::get) take the same per-root stripes, so they can't observe
+    /// a slot mid-merge either. If re-checking under the lock finds that either root
+    /// changed underneath us (another thread merged through one of them first), we
+    /// restart from `find` rather than merging a stale root.
+    ///
+    /// The root with the smaller key always wins and keeps the combined value; the
+    /// `Left`/`Right` tag returned by [`Union::union`] is not used here.
+    pub fn try_union(&self, key0: usize, key1: usize) -> bool {
+        loop {
+            let k0 = self.find(key0);
+            let k1 = self.find(key1);
+            if k0 == k1 {
+                return false;
+            }
+            let (winner, loser) = if k0 < k1 { (k0, k1) } else { (k1, k0) };
+
+            // Lock stripes in a fixed order (by stripe index, not root index) so two
+            // calls that need the same pair of stripes never deadlock against each
+            // other.
+            let winner_shard = winner % self.payload_locks.len();
+            let loser_shard = loser % self.payload_locks.len();
+            let (low, high) = if winner_shard <= loser_shard {
+                (winner_shard, loser_shard)
+            } else {
+                (loser_shard, winner_shard)
+            };
+            let _low_guard = self.payload_locks[low].lock().unwrap();
+            let _high_guard = if high != low {
+                Some(self.payload_locks[high].lock().unwrap())
+            } else {
+                None
+            };
+
+            // Someone may have reparented one of these roots while we were waiting
+            // for the lock (or before we took it); restart from `find` rather than
+            // merge a stale root.
+            if *self.link_parent.get(&winner).unwrap() != winner
+                || *self.link_parent.get(&loser).unwrap() != loser
+            {
+                continue;
+            }
+
+            // SAFETY: we hold both `winner` and `loser`'s stripes for the rest of
+            // this iteration, and every `try_union`/`get` call takes a slot's stripe
+            // before touching it, so no other call can be touching these slots. We
+            // only ever form a *shared* reference to the backing `Vec` (`as_ptr`,
+            // not `as_mut_ptr`), since other threads may be forming their own shared
+            // reference to it concurrently (e.g. via `get`/`size`/`Debug::fmt`); the
+            // stripes are what make writing through the resulting raw pointers sound,
+            // not exclusive access to the `Vec` itself.
+            unsafe {
+                let base = (*self.payload.get()).as_ptr() as *mut Option<V>;
+                let winner_slot = &mut *base.add(winner);
+                let loser_slot = &mut *base.add(loser);
+                let v_winner = winner_slot.take().unwrap();
+                let v_loser = loser_slot.take().unwrap();
+                *winner_slot = Some(match Union::union(v_winner, v_loser) {
+                    UnionResult::Left(val) | UnionResult::Right(val) => val,
+                });
+            }
+
+            let _ = self.link_parent.insert(loser, winner);
+
+            return true;
+        }
+    }
+}
+
+impl<V: Union> QuickUnionUf<V> {
+    /// Find the representative of `key`'s set using two-pass full path compression:
+    /// walk up to the root recording every node visited along the way, then reseat
+    /// each of those nodes directly to the root in a second pass. This does one
+    /// `DashMap` write per already-visited node instead of [`find`](UnionFind::find)'s
+    /// one-write-per-halving-step, which pays off under heavy reuse of the same deep
+    /// chains. Takes `&mut self` so the scratch buffer can be reused across calls
+    /// without synchronization.
+    pub fn find_compress(&mut self, key: usize) -> usize {
+        debug_assert!(self.find_parent_list.is_empty());
+
+        let mut k = key;
+        loop {
+            let p = *self.link_parent.get(&k).unwrap();
+            if p == k {
+                break;
+            }
+            self.find_parent_list.push(k);
+            k = p;
+        }
+        let root = k;
+
+        for node in self.find_parent_list.drain(..) {
+            if self.snapshot_depth.load(Ordering::SeqCst) > 0 {
+                let old_parent = *self.link_parent.get(&node).unwrap();
+                self.undo_log.lock().unwrap().push(UndoEntry::LinkChanged {
+                    key: node,
+                    old_parent,
+                });
+            }
+            let _ = self.link_parent.insert(node, root);
+        }
+
+        root
+    }
+}
+
+impl<V: Union + Default> QuickUnionUf<V> {
+    /// Reset every set to a singleton (`link_parent[i] == i`) so a sized arena can be
+    /// reused across many computations without dropping and reallocating the backing
+    /// `DashMap` and payload `Vec`. Elements whose slot still holds a value (the roots
+    /// of their sets, including any merged value they carry) are reset in place via
+    /// [`Union::clear`], which defaults to a no-op; elements a prior `union` emptied
+    /// get a fresh `V::default()`, since their original value no longer exists to reset.
+    pub fn clear(&mut self) {
+        assert_eq!(
+            self.snapshot_depth.load(Ordering::SeqCst),
+            0,
+            "clear() while a snapshot is open would let a later rollback_to replay \
+             pre-clear parents/values over the freshly cleared arena"
+        );
+        self.undo_log.get_mut().unwrap().clear();
+
+        let len = self.payload_mut().len();
+        for i in 0..len {
+            let _ = self.link_parent.insert(i, i);
+            match self.payload_mut()[i].as_mut() {
+                Some(value) => Union::clear(value),
+                None => self.payload_mut()[i] = Some(V::default()),
+            }
+        }
+        self.find_parent_list.clear();
     }
 }
 
@@ -100,7 +525,11 @@ impl<A: Union> FromIterator<A> for QuickUnionUf<A> {
     fn from_iter<T: IntoIterator<Item = A>>(iterator: T) -> QuickUnionUf<A> {
         let mut uf = QuickUnionUf {
             link_parent: Default::default(),
-            payload: vec![],
+            payload: UnsafeCell::new(vec![]),
+            undo_log: Mutex::new(Vec::new()),
+            snapshot_depth: AtomicUsize::new(0),
+            find_parent_list: Vec::new(),
+            payload_locks: new_payload_locks(),
         };
         uf.extend(iterator);
         uf
@@ -113,11 +542,453 @@ impl<A> Extend<A> for QuickUnionUf<A> {
     where
         T: IntoIterator<Item = A>,
     {
-        let len = self.payload.len();
+        let len = self.payload.get_mut().len();
         let payload = iterable.into_iter().map(Some);
-        self.payload.extend(payload);
+        self.payload.get_mut().extend(payload);
 
-        let new_len = self.payload.len();
+        let new_len = self.payload.get_mut().len();
         self.link_parent.extend((len..new_len).map(|x| (x, x)));
     }
 }
+
+/// A typed handle into a [`UnificationTable`], pairing a newtype key with the
+/// `usize` index it wraps. Mirrors the `ena` crate's `UnifyKey` trait so constraint
+/// solvers and type-checkers can work with typed variables instead of raw indices.
+pub trait UnifyKey: Copy {
+    /// The value every representative of this key's set carries.
+    type Value: UnifyValue;
+
+    /// This key's underlying index into the backing [`QuickUnionUf`].
+    fn index(&self) -> usize;
+
+    /// Construct the key wrapping index `index`.
+    fn from_index(index: usize) -> Self;
+}
+
+/// A value that can be combined with another instance of itself when two
+/// [`UnifyKey`]s are unified, and may reject the combination (e.g. two incompatible
+/// type constraints).
+pub trait UnifyValue: Clone + Union {
+    /// The reason `a` and `b` could not be merged.
+    type Error;
+
+    /// Attempt to combine `a` and `b` into the value their merged set should carry.
+    fn unify(a: &Self, b: &Self) -> Result<Self, Self::Error>;
+}
+
+/// A [`QuickUnionUf`]-backed table of typed unification variables, supporting
+/// fallible value merging via [`UnifyValue::unify`].
+///
+/// Unlike [`UnionFind::union`], [`unify_var_var`](Self::unify_var_var) and
+/// [`unify_var_value`](Self::unify_var_value) can fail: on conflict the table is left
+/// exactly as it was, so a failed unification needs no undoing. The table keeps its
+/// backing [`QuickUnionUf`] private and has no snapshot or rollback methods of its own.
+#[derive(Debug, Clone)]
+pub struct UnificationTable<K: UnifyKey> {
+    table: QuickUnionUf<K::Value>,
+}
+
+impl<K: UnifyKey> Default for UnificationTable<K> {
+    fn default() -> Self {
+        UnificationTable::new()
+    }
+}
+
+impl<K: UnifyKey> UnificationTable<K> {
+    /// Create an empty table.
+    pub fn new() -> Self {
+        UnificationTable {
+            table: QuickUnionUf::from_iter(std::iter::empty::<K::Value>()),
+        }
+    }
+
+    /// Allocate a fresh key carrying `value`.
+    pub fn new_key(&mut self, value: K::Value) -> K {
+        K::from_index(self.table.insert(value))
+    }
+
+    /// Read the value carried by `key`'s representative.
+    pub fn probe_value(&self, key: K) -> K::Value {
+        self.table.get(key.index()).clone()
+    }
+
+    /// Unify `a` and `b`'s sets, merging their values with [`UnifyValue::unify`]. On
+    /// conflict, the table is left unchanged.
+    pub fn unify_var_var(&mut self, a: K, b: K) -> Result<(), <K::Value as UnifyValue>::Error> {
+        self.table
+            .try_merge(a.index(), b.index(), UnifyValue::unify)?;
+        Ok(())
+    }
+
+    /// Unify `a`'s set with a bare value. On conflict, the table is left unchanged.
+    pub fn unify_var_value(
+        &mut self,
+        a: K,
+        value: K::Value,
+    ) -> Result<(), <K::Value as UnifyValue>::Error> {
+        let root = self.table.find(a.index());
+        let merged = UnifyValue::unify(self.table.get(root), &value)?;
+        self.table.set_payload_logged(root, Some(merged));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod unify_tests {
+    use super::*;
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    struct TypeVar(usize);
+
+    impl UnifyKey for TypeVar {
+        type Value = TypeVal;
+
+        fn index(&self) -> usize {
+            self.0
+        }
+
+        fn from_index(index: usize) -> Self {
+            TypeVar(index)
+        }
+    }
+
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    enum TypeVal {
+        Unbound,
+        Int,
+        Bool,
+    }
+
+    impl Union for TypeVal {
+        // `QuickUnionUf::union`'s infallible merge is never exercised through
+        // `UnificationTable`, which always goes through `UnifyValue::unify` instead;
+        // this only exists to satisfy `UnifyValue: Union`.
+        fn union(left: Self, _right: Self) -> UnionResult<Self> {
+            UnionResult::Left(left)
+        }
+    }
+
+    #[derive(Debug, PartialEq, Eq)]
+    struct Conflict(&'static str);
+
+    impl UnifyValue for TypeVal {
+        type Error = Conflict;
+
+        fn unify(a: &Self, b: &Self) -> Result<Self, Conflict> {
+            match (a, b) {
+                (TypeVal::Unbound, other) | (other, TypeVal::Unbound) => Ok(other.clone()),
+                (a, b) if a == b => Ok(a.clone()),
+                _ => Err(Conflict("incompatible types")),
+            }
+        }
+    }
+
+    #[test]
+    fn unify_var_var_merges_compatible_bindings() {
+        let mut table: UnificationTable<TypeVar> = UnificationTable::new();
+        let a = table.new_key(TypeVal::Unbound);
+        let b = table.new_key(TypeVal::Int);
+
+        table.unify_var_var(a, b).unwrap();
+
+        assert_eq!(table.probe_value(a), TypeVal::Int);
+        assert_eq!(table.probe_value(b), TypeVal::Int);
+    }
+
+    #[test]
+    fn unify_var_value_rejects_conflicts_without_mutating() {
+        let mut table: UnificationTable<TypeVar> = UnificationTable::new();
+        let a = table.new_key(TypeVal::Int);
+
+        let err = table.unify_var_value(a, TypeVal::Bool).unwrap_err();
+
+        assert_eq!(err, Conflict("incompatible types"));
+        assert_eq!(table.probe_value(a), TypeVal::Int);
+    }
+
+    #[test]
+    fn unify_var_value_merge_is_undone_by_rollback() {
+        let mut table: UnificationTable<TypeVar> = UnificationTable::new();
+        let a = table.new_key(TypeVal::Unbound);
+
+        let snap = table.table.snapshot();
+        table.unify_var_value(a, TypeVal::Int).unwrap();
+        assert_eq!(table.probe_value(a), TypeVal::Int);
+
+        table.table.rollback_to(snap);
+
+        assert_eq!(table.probe_value(a), TypeVal::Unbound);
+    }
+}
+
+#[cfg(test)]
+mod compress_and_clear_tests {
+    use super::*;
+
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    struct Count(u32);
+
+    impl Union for Count {
+        fn union(left: Self, right: Self) -> UnionResult<Self> {
+            UnionResult::Left(Count(left.0 + right.0))
+        }
+
+        fn clear(&mut self) {
+            self.0 = 1;
+        }
+    }
+
+    impl Default for Count {
+        fn default() -> Self {
+            Count(1)
+        }
+    }
+
+    #[test]
+    fn find_compress_points_every_visited_node_at_the_root() {
+        let mut uf: QuickUnionUf<Count> = (0..5).map(|_| Count(1)).collect();
+        uf.union(0, 1);
+        uf.union(1, 2);
+        uf.union(2, 3);
+
+        let root = uf.find_compress(0);
+        assert_eq!(uf.find_compress(3), root);
+        assert_eq!(uf.find(0), root);
+        assert_eq!(uf.find(1), root);
+        assert_eq!(uf.find(2), root);
+    }
+
+    #[test]
+    fn clear_resets_links_and_invokes_union_clear() {
+        let mut uf: QuickUnionUf<Count> = (0..4).map(|_| Count(1)).collect();
+        uf.union(0, 1);
+        uf.union(2, 3);
+        assert_eq!(uf.get(0).0, 2);
+
+        uf.clear();
+
+        for key in 0..4 {
+            assert_eq!(uf.find(key), key);
+            assert_eq!(uf.get(key).0, 1);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "clear() while a snapshot is open")]
+    fn clear_rejects_being_called_with_an_open_snapshot() {
+        let mut uf: QuickUnionUf<Count> = (0..2).map(|_| Count(1)).collect();
+        let _snap = uf.snapshot();
+        uf.clear();
+    }
+}
+
+#[cfg(test)]
+mod concurrent_tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    struct Count(u32);
+
+    impl Union for Count {
+        fn union(left: Self, right: Self) -> UnionResult<Self> {
+            UnionResult::Left(Count(left.0 + right.0))
+        }
+    }
+
+    #[test]
+    fn try_union_from_multiple_threads_merges_every_element_once() {
+        const N: usize = 64;
+        let uf: Arc<QuickUnionUf<Count>> = Arc::new((0..N).map(|_| Count(1)).collect());
+
+        let handles: Vec<_> = (0..N - 1)
+            .map(|i| {
+                let uf = Arc::clone(&uf);
+                thread::spawn(move || {
+                    uf.try_union(i, i + 1);
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let root = uf.find(0);
+        for key in 1..N {
+            assert_eq!(uf.find(key), root);
+        }
+        assert_eq!(uf.get(root).0, N as u32);
+    }
+
+    #[test]
+    fn try_union_star_pattern_into_one_winner_never_races() {
+        // Every thread merges a distinct key into key 0, so they all agree on the
+        // winner but disagree on the loser -- the case a CAS on the loser's entry
+        // alone can't protect.
+        const N: usize = 2000;
+        let uf: Arc<QuickUnionUf<Count>> = Arc::new((0..N).map(|_| Count(1)).collect());
+
+        let handles: Vec<_> = (1..N)
+            .map(|i| {
+                let uf = Arc::clone(&uf);
+                thread::spawn(move || {
+                    uf.try_union(0, i);
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let root = uf.find(0);
+        for key in 1..N {
+            assert_eq!(uf.find(key), root);
+        }
+        assert_eq!(uf.get(root).0, N as u32);
+    }
+
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    struct SlowCount(u32);
+
+    impl Union for SlowCount {
+        fn union(left: Self, right: Self) -> UnionResult<Self> {
+            // Widen the window in which `try_union`'s payload slots are mid-merge
+            // (both taken, neither yet holding the combined value), so a concurrent
+            // `get` that isn't synchronized with `try_union` reliably observes it.
+            thread::sleep(std::time::Duration::from_millis(50));
+            UnionResult::Left(SlowCount(left.0 + right.0))
+        }
+    }
+
+    #[test]
+    fn get_never_observes_try_union_mid_merge() {
+        let uf: Arc<QuickUnionUf<SlowCount>> =
+            Arc::new((0..2).map(|_| SlowCount(1)).collect());
+
+        let merger = {
+            let uf = Arc::clone(&uf);
+            thread::spawn(move || {
+                uf.try_union(0, 1);
+            })
+        };
+        // Give `try_union` a head start so it's inside its critical section (and
+        // `Union::union` is sleeping) by the time `get` runs. Without synchronizing
+        // `get` against `try_union`'s stripe, this reliably observed the winner
+        // slot's `None` mid-merge and panicked; now `get` blocks until the merge
+        // finishes instead, so it only ever sees the pre- or post-merge value.
+        thread::sleep(std::time::Duration::from_millis(10));
+        assert_eq!(uf.get(0).0, 2);
+
+        merger.join().unwrap();
+        assert_eq!(uf.get(0).0, 2);
+    }
+}
+
+#[cfg(test)]
+mod snapshot_tests {
+    use super::*;
+
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    struct Rank(u32);
+
+    impl Union for Rank {
+        fn union(left: Self, right: Self) -> UnionResult<Self> {
+            if left.0 >= right.0 {
+                UnionResult::Left(Rank(left.0.max(right.0 + 1)))
+            } else {
+                UnionResult::Right(Rank(right.0.max(left.0 + 1)))
+            }
+        }
+    }
+
+    #[test]
+    fn rollback_undoes_unions_and_path_compression() {
+        let mut uf: QuickUnionUf<Rank> = (0..5).map(Rank).collect();
+        uf.union(0, 1);
+
+        let snap = uf.snapshot();
+        uf.union(1, 2);
+        uf.union(2, 3);
+        let _ = uf.find(0); // path-compress (0 -> 2 -> 3) while the snapshot is live
+
+        uf.rollback_to(snap);
+
+        assert_eq!(uf.find(0), uf.find(1));
+        assert_ne!(uf.find(0), uf.find(2));
+        assert_ne!(uf.find(2), uf.find(3));
+        assert_eq!(*uf.get(2), Rank(2));
+        assert_eq!(*uf.get(3), Rank(3));
+    }
+
+    #[test]
+    fn nested_commit_keeps_outer_rollback_working() {
+        let mut uf: QuickUnionUf<Rank> = (0..3).map(Rank).collect();
+
+        let outer = uf.snapshot();
+        let inner = uf.snapshot();
+        uf.union(0, 1);
+        uf.commit(inner);
+        uf.union(1, 2);
+
+        uf.rollback_to(outer);
+
+        assert_ne!(uf.find(0), uf.find(1));
+        assert_ne!(uf.find(1), uf.find(2));
+    }
+
+    #[test]
+    fn rollback_undoes_find_compress_path_compression() {
+        let mut uf: QuickUnionUf<Rank> = (0..4).map(Rank).collect();
+        // Wire up a 3-hop chain directly (bypassing `union`'s own path-halving finds)
+        // so `find_compress` actually has intermediate links to reseat.
+        uf.link_parent.insert(0, 1);
+        uf.link_parent.insert(1, 2);
+        uf.link_parent.insert(2, 3);
+
+        let snap = uf.snapshot();
+        let root = uf.find_compress(0);
+        assert_eq!(root, 3);
+        assert_eq!(*uf.link_parent.get(&0).unwrap(), 3);
+        assert_eq!(*uf.link_parent.get(&1).unwrap(), 3);
+
+        uf.rollback_to(snap);
+
+        assert_eq!(*uf.link_parent.get(&0).unwrap(), 1);
+        assert_eq!(*uf.link_parent.get(&1).unwrap(), 2);
+        assert_eq!(*uf.link_parent.get(&2).unwrap(), 3);
+    }
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod serde_tests {
+    use super::*;
+
+    #[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+    struct Rank(u32);
+
+    impl Union for Rank {
+        fn union(left: Self, right: Self) -> UnionResult<Self> {
+            if left.0 >= right.0 {
+                UnionResult::Left(Rank(left.0.max(right.0 + 1)))
+            } else {
+                UnionResult::Right(Rank(right.0.max(left.0 + 1)))
+            }
+        }
+    }
+
+    #[test]
+    fn round_trips_through_serde_json() {
+        let mut uf: QuickUnionUf<Rank> = (0..6).map(Rank).collect();
+        uf.union(0, 1);
+        uf.union(2, 3);
+        uf.union(3, 4);
+
+        let json = serde_json::to_string(&uf).unwrap();
+        let restored: QuickUnionUf<Rank> = serde_json::from_str(&json).unwrap();
+
+        for key in 0..6 {
+            assert_eq!(uf.find(key), restored.find(key));
+        }
+    }
+}